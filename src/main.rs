@@ -1,5 +1,6 @@
 use std::io::Read;
-use std::path::PathBuf;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
 use std::{collections::HashMap, fs, io};
 
 use clap::{Args, Parser, Subcommand};
@@ -11,6 +12,32 @@ const CTX_NL: &str = "\n";
 const CTX_EOL: &str = "\n";
 const CTX_MID: &str = "\n";
 
+/// Marker written as the first line of `-H` output; lets `apply` detect and
+/// reject a human readable diff instead of misparsing it.
+const FFWX_HUMAN_MARKER: &str = "# ffwx-human";
+
+/// Written right after a line's value when the source file has no trailing
+/// newline and this is its last line.
+const NO_NEWLINE_SOURCE: &str = "\\ No newline at end of source file";
+/// Written right after a line's value when the modified file has no
+/// trailing newline and this is its last line.
+const NO_NEWLINE_MODIFIED: &str = "\\ No newline at end of modified file";
+/// Written right after a `Changed` line's value, carrying the line it
+/// replaced. `apply` uses it to verify an anchor names the same source line
+/// the diff was generated against, instead of trusting context alone.
+const CHANGED_SOURCE_PREFIX: &str = "\\ was: ";
+
+/// Precedes a changed file's diff within a directory-mode ffwx stream,
+/// followed immediately by its path relative to the source/modified roots.
+const FILE_DIFF_MARKER: &str = "@@@ file ";
+/// Precedes the whole-file diff (all `Added` lines) for a path that exists
+/// only in the modified tree.
+const FILE_ADDED_MARKER: &str = "@@@ added ";
+/// Precedes the whole-file diff (all `Removed` lines) for a path that exists
+/// only in the source tree; applying it leaves the path out of the output
+/// tree instead of writing an emptied file.
+const FILE_REMOVED_MARKER: &str = "@@@ removed ";
+
 #[derive(Debug, Parser)]
 #[command(name = "ffwx")]
 #[command(author = "Nullenbox")]
@@ -38,11 +65,11 @@ enum Command {
 
 #[derive(Debug, Args)]
 struct DiffArgs {
-    ///Path to the source file
+    ///Path to the source file, or a directory to diff recursively
     #[arg(short)]
     source_file: String,
 
-    ///Path to the modified file
+    ///Path to the modified file, or a directory to diff recursively
     #[arg(short)]
     modified_file: String,
 
@@ -53,6 +80,16 @@ struct DiffArgs {
     ///Write context lines separately
     #[arg(short = 'H')]
     human_readable: bool,
+
+    ///Use the patience diff algorithm instead of Myers; tends to produce
+    ///more human-meaningful hunks on reordered/structured text
+    #[arg(long = "patience")]
+    patience: bool,
+
+    ///Emit a standard unified diff instead of the ffwx format; takes an
+    ///optional context line count (defaults to 3), e.g. `-U` or `-U 5`
+    #[arg(short = 'U', long = "unified", num_args = 0..=1, default_missing_value = "3")]
+    unified: Option<usize>,
 }
 
 #[derive(Debug, Args)]
@@ -61,16 +98,28 @@ struct ApplyArgs {
     #[arg(short)]
     ffwx_file: String,
 
-    ///Path to the source file
+    ///Path to the source file, or a directory when applying a directory-mode
+    ///ffwx stream
     #[arg(short)]
     source_file: String,
+
+    ///Directory to write the reconstructed tree into; required when
+    ///applying against a directory (ignored for single-file apply, which
+    ///still prints to stdout)
+    #[arg(short = 'o', long = "out-dir")]
+    output_dir: Option<String>,
 }
 
-fn get_lines_from_file(path: &PathBuf) -> Result<Vec<String>, io::Error> {
+/// Reads `path` into lines, alongside whether the file is missing its
+/// trailing newline -- `contents.lines()` throws that information away, but
+/// `apply` needs it to reproduce the modified file byte-for-byte.
+fn get_lines_from_file(path: &PathBuf) -> Result<(Vec<String>, bool), io::Error> {
     let mut file = fs::File::open(path)?;
     let mut contents = String::new();
     file.read_to_string(&mut contents)?;
-    Ok(contents.lines().map(|s| s.to_string()).collect())
+    let missing_trailing_newline = !contents.is_empty() && !contents.ends_with('\n');
+    let lines = contents.lines().map(|s| s.to_string()).collect();
+    Ok((lines, missing_trailing_newline))
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -153,7 +202,7 @@ impl LineCtx {
     }
 
     fn after_str(&self) -> String {
-        let start = if self.before.is_empty() { "" } else { CTX_NL };
+        let start = if self.after.is_empty() { "" } else { CTX_NL };
         format!("{}{}", start, self.after.join(CTX_NL))
     }
 }
@@ -163,6 +212,18 @@ struct DiffLine {
     kind: DiffKind,
     value: String,
     ctx: LineCtx,
+    /// Set when this line is the last line of the source file and that file
+    /// has no trailing newline.
+    source_missing_nl: bool,
+    /// Set when this line is the last line of the modified file and that
+    /// file has no trailing newline.
+    modified_missing_nl: bool,
+    /// For `Changed` lines, the source line this one replaces -- lets
+    /// `apply` verify its anchor by value, not just by context. `None` for
+    /// `Added`/`Removed` lines (the value itself already is, or isn't, the
+    /// source line) and for `Changed` lines parsed from an older ffwx file
+    /// that predates this marker.
+    source_value: Option<String>,
 }
 impl DiffLine {
     fn new(kind: DiffKind, value: String) -> Self {
@@ -170,6 +231,9 @@ impl DiffLine {
             kind,
             value,
             ctx: LineCtx::new(),
+            source_missing_nl: false,
+            modified_missing_nl: false,
+            source_value: None,
         }
     }
 
@@ -181,121 +245,638 @@ impl DiffLine {
         DiffLine::new(DiffKind::Removed, value)
     }
 
-    fn changed(value: String) -> Self {
-        DiffLine::new(DiffKind::Changed, value)
+    fn changed(value: String, source_value: String) -> Self {
+        let mut line = DiffLine::new(DiffKind::Changed, value);
+        line.source_value = Some(source_value);
+        line
     }
 }
 
-fn compute_diff(source: Vec<String>, modified: Vec<String>) -> Vec<DiffLine> {
+/// One step of a Myers edit script, indexing into the original `source` (for
+/// `Equal`/`Delete`) and `modified` (for `Equal`/`Insert`) slices.
+#[derive(Debug, Clone, Copy)]
+enum EditOp {
+    Equal(usize, usize),
+    Delete(usize),
+    Insert(usize),
+}
+
+/// Runs the forward pass of Myers' O(ND) algorithm, snapshotting the
+/// furthest-reaching `x` per diagonal `k` before each round `d` so
+/// `myers_backtrack` can replay the same decisions in reverse.
+fn myers_trace(source: &[String], modified: &[String]) -> Vec<HashMap<isize, isize>> {
+    let n = source.len() as isize;
+    let m = modified.len() as isize;
+    let max = n + m;
+
+    let mut v: HashMap<isize, isize> = HashMap::new();
+    v.insert(1, 0);
+    let mut trace: Vec<HashMap<isize, isize>> = Vec::new();
+
+    for d in 0..=max {
+        trace.push(v.clone());
+        for k in (-d..=d).step_by(2) {
+            let down = k == -d
+                || (k != d
+                    && v.get(&(k - 1)).copied().unwrap_or(0) < v.get(&(k + 1)).copied().unwrap_or(0));
+
+            let mut x = if down {
+                v.get(&(k + 1)).copied().unwrap_or(0)
+            } else {
+                v.get(&(k - 1)).copied().unwrap_or(0) + 1
+            };
+            let mut y = x - k;
+
+            while x < n && y < m && source[x as usize] == modified[y as usize] {
+                x += 1;
+                y += 1;
+            }
+
+            v.insert(k, x);
+
+            if x >= n && y >= m {
+                return trace;
+            }
+        }
+    }
+
+    trace
+}
+
+/// Walks a `myers_trace` backwards from `(source.len(), modified.len())` to
+/// `(0, 0)`, emitting the edit script in forward order.
+fn myers_backtrack(source: &[String], modified: &[String], trace: &[HashMap<isize, isize>]) -> Vec<EditOp> {
+    let mut ops: Vec<EditOp> = Vec::new();
+    let mut x = source.len() as isize;
+    let mut y = modified.len() as isize;
+
+    for d in (0..trace.len()).rev() {
+        let d = d as isize;
+        let v = &trace[d as usize];
+        let k = x - y;
+
+        let down = k == -d
+            || (k != d
+                && v.get(&(k - 1)).copied().unwrap_or(0) < v.get(&(k + 1)).copied().unwrap_or(0));
+
+        let prev_k = if down { k + 1 } else { k - 1 };
+        let prev_x = v.get(&prev_k).copied().unwrap_or(0);
+        let prev_y = prev_x - prev_k;
+
+        while x > prev_x && y > prev_y {
+            ops.push(EditOp::Equal((x - 1) as usize, (y - 1) as usize));
+            x -= 1;
+            y -= 1;
+        }
+
+        if d > 0 {
+            if down {
+                ops.push(EditOp::Insert(prev_y as usize));
+            } else {
+                ops.push(EditOp::Delete(prev_x as usize));
+            }
+        }
+
+        x = prev_x;
+        y = prev_y;
+    }
+
+    ops.reverse();
+    ops
+}
+
+/// Runs the full Myers algorithm and returns just the edit script, for
+/// callers (like the unified diff writer) that want the raw operations
+/// rather than `ffwx`'s grouped `DiffLine`s.
+fn myers_ops(source: &[String], modified: &[String]) -> Vec<EditOp> {
+    let trace = myers_trace(source, modified);
+    myers_backtrack(source, modified, &trace)
+}
+
+/// Where a `DiffLine` should be anchored back into `source` when `apply`
+/// later needs to rediscover its position.
+enum ContextAnchor {
+    /// `Removed`/`Changed`: the line itself sits at this `source` index.
+    At(usize),
+    /// Same as `At`, but without an `after` context line. Used for a
+    /// synthesized trailing-newline marker: the `source` line right after it
+    /// may be on its way out (e.g. a removed trailing line), so anchoring to
+    /// it would tie this entry's position to content that's about to
+    /// disappear instead of content that will still be there.
+    AtNoAfter(usize),
+    /// `Added`: no `source` line corresponds to it; this is the index of the
+    /// first unconsumed `source` line right after the insertion point.
+    Gap(usize),
+}
+
+/// The `source` index an anchor is ordered by, so synthesized entries can be
+/// inserted in the right place among already-ordered ones.
+fn anchor_key(anchor: &ContextAnchor) -> usize {
+    match anchor {
+        ContextAnchor::At(si) | ContextAnchor::AtNoAfter(si) => *si,
+        ContextAnchor::Gap(gap) => *gap,
+    }
+}
+
+/// Groups an edit script into `DiffLine`s, pairing adjacent delete+insert
+/// runs into `Changed` lines and leaving the rest as `Removed`/`Added`.
+/// Context (`LineCtx`) is filled in separately by `populate_context`. Also
+/// returns, per line, the `modified` index it came from (if any) -- `None`
+/// for `Removed` lines -- which `ensure_trailing_newline_markers` needs to
+/// tell whether a line is the modified file's last one.
+fn ops_to_diff_lines(
+    source: &[String],
+    modified: &[String],
+    ops: &[EditOp],
+) -> (Vec<DiffLine>, Vec<ContextAnchor>, Vec<Option<usize>>) {
     let mut lines: Vec<DiffLine> = Vec::new();
-    /*
-    if lines are equal skip
-    else cross compare the current line of each file until find a match
-        while doing so collect all lines in separate buffers (one for each file)
-        stop after finding a match or hit end of both files
-    at the end if both buffers have the same number of lines
-    then all lines on the modified buffer are "changed"
-    else if match was found on the source file then lines are "removed" and if match was found on the modified file then lines are "added"
-    */
-
-    let mut i: usize = 0;
-    let mut j: usize = 0;
-
-    loop {
-        if i >= source.len() && j >= modified.len() {
-            break;
-        }
-        let (sline, mline) = (source.get(i), modified.get(j));
-        match (sline, mline) {
-            (Some(s), Some(m)) => {
-                if s == m {
-                    i += 1;
-                    j += 1;
-                    continue;
-                }
-                let mut src_buf: Vec<String> = vec![s.clone()];
-                let mut mod_buf: Vec<String> = vec![m.clone()];
-
-                let mut x: usize = 1;
-                let mut y: usize = 1;
-
-                loop {
-                    let ns = source.get(i + x);
-                    let nm = modified.get(j + y);
-
-                    match (ns, nm) {
-                        (Some(ns), Some(nm)) => {
-							if ns == nm {
-								break;
-							} else {
-								src_buf.push(ns.clone());
-								mod_buf.push(nm.clone());
-							}
-							if m == ns {
-								src_buf.pop();
-								mod_buf.pop();
-								mod_buf.pop();
-                                break;
-                            }
-							if s == nm {
-								src_buf.pop();
-								src_buf.pop();
-								mod_buf.pop();
-                                break;
-                            }
-                        }
-                        (Some(ns), None) => {
-                            if m == ns {
-                                break;
-                            }
-                            src_buf.push(ns.clone());
-                        }
-                        (None, Some(nm)) => {
-                            if s == nm {
-                                break;
-                            }
-                            mod_buf.push(nm.clone());
-                        }
-                        (None, None) => break,
-                    }
+    let mut anchors: Vec<ContextAnchor> = Vec::new();
+    let mut mod_indices: Vec<Option<usize>> = Vec::new();
 
-                    x += 1;
-                    y += 1;
-                }
-                if mod_buf.len() == src_buf.len() && !mod_buf.is_empty() {
-                    for str in mod_buf {
-                        let line = DiffLine::changed(str);
-                        lines.push(line);
+    let mut i = 0;
+    while i < ops.len() {
+        if matches!(ops[i], EditOp::Equal(_, _)) {
+            i += 1;
+            continue;
+        }
+
+        let mut dels: Vec<usize> = Vec::new();
+        let mut inss: Vec<usize> = Vec::new();
+        let mut j = i;
+        while j < ops.len() {
+            match ops[j] {
+                EditOp::Delete(si) => dels.push(si),
+                EditOp::Insert(mi) => inss.push(mi),
+                EditOp::Equal(_, _) => break,
+            }
+            j += 1;
+        }
+
+        let gap = if let Some(&last_del) = dels.last() {
+            last_del + 1
+        } else if let Some(EditOp::Equal(si, _)) = ops.get(j) {
+            *si
+        } else {
+            source.len()
+        };
+
+        let paired = dels.len().min(inss.len());
+        for k in 0..paired {
+            lines.push(DiffLine::changed(modified[inss[k]].clone(), source[dels[k]].clone()));
+            anchors.push(ContextAnchor::At(dels[k]));
+            mod_indices.push(Some(inss[k]));
+        }
+        for k in paired..dels.len() {
+            lines.push(DiffLine::removed(source[dels[k]].clone()));
+            anchors.push(ContextAnchor::At(dels[k]));
+            mod_indices.push(None);
+        }
+        for k in paired..inss.len() {
+            lines.push(DiffLine::added(modified[inss[k]].clone()));
+            anchors.push(ContextAnchor::Gap(gap));
+            mod_indices.push(Some(inss[k]));
+        }
+
+        i = j;
+    }
+
+    (lines, anchors, mod_indices)
+}
+
+/// Post-pass over the grouped diff lines: fills each `LineCtx` with the
+/// single `source` line immediately before/after its anchor, so `apply` can
+/// locate it again even if line numbers have since shifted.
+fn populate_context(source: &[String], lines: &mut [DiffLine], anchors: &[ContextAnchor]) {
+    for (line, anchor) in lines.iter_mut().zip(anchors) {
+        let (before_idx, after_idx) = match anchor {
+            ContextAnchor::At(si) => (si.checked_sub(1), Some(si + 1)),
+            ContextAnchor::AtNoAfter(si) => (si.checked_sub(1), None),
+            ContextAnchor::Gap(gap) => (gap.checked_sub(1), Some(*gap)),
+        };
+        if let Some(bi) = before_idx {
+            if let Some(v) = source.get(bi) {
+                line.ctx.before.push(v.clone());
+            }
+        }
+        if let Some(ai) = after_idx {
+            if let Some(v) = source.get(ai) {
+                line.ctx.after.push(v.clone());
+            }
+        }
+    }
+}
+
+/// Which edit-script algorithm `compute_diff` (and the `-U` writer) should
+/// use to turn `source`/`modified` into an ordered list of `EditOp`s.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DiffAlgorithm {
+    Myers,
+    Patience,
+}
+
+fn run_algorithm(source: &[String], modified: &[String], algorithm: DiffAlgorithm) -> Vec<EditOp> {
+    match algorithm {
+        DiffAlgorithm::Myers => myers_ops(source, modified),
+        DiffAlgorithm::Patience => patience_ops(source, modified),
+    }
+}
+
+/// Finds the `modified` index that `source[si]` was matched to by an
+/// `Equal` op, if `si` was left untouched by the diff.
+fn equal_pair_for_source(ops: &[EditOp], si: usize) -> Option<usize> {
+    ops.iter().find_map(|op| match op {
+        EditOp::Equal(s, m) if *s == si => Some(*m),
+        _ => None,
+    })
+}
+
+/// Finds the `source` index that `modified[mi]` was matched to by an
+/// `Equal` op, if `mi` was left untouched by the diff.
+fn equal_pair_for_modified(ops: &[EditOp], mi: usize) -> Option<usize> {
+    ops.iter().find_map(|op| match op {
+        EditOp::Equal(s, m) if *m == mi => Some(*s),
+        _ => None,
+    })
+}
+
+/// Inserts a synthesized entry at the position its anchor belongs at, rather
+/// than at the end, so `lines`/`anchors` stay in the ascending anchor order
+/// `apply_diff`'s single forward cursor requires.
+fn insert_by_anchor(
+    lines: &mut Vec<DiffLine>,
+    anchors: &mut Vec<ContextAnchor>,
+    mod_indices: &mut Vec<Option<usize>>,
+    line: DiffLine,
+    anchor: ContextAnchor,
+    mod_index: Option<usize>,
+) {
+    let pos = anchors.iter().position(|a| anchor_key(a) > anchor_key(&anchor)).unwrap_or(anchors.len());
+    lines.insert(pos, line);
+    anchors.insert(pos, anchor);
+    mod_indices.insert(pos, mod_index);
+}
+
+/// Which of `source`/`modified` lack a trailing newline, threaded through as
+/// one value instead of two flags to keep `ensure_trailing_newline_markers`'s
+/// argument count down.
+#[derive(Debug, Clone, Copy)]
+struct MissingNewlines {
+    source: bool,
+    modified: bool,
+}
+
+/// Makes sure a missing trailing newline on either file surfaces somewhere
+/// in `lines`, even when the last line itself is unchanged (and so wouldn't
+/// otherwise produce a `DiffLine` at all): it tags whichever line already
+/// covers that last index, or -- if the last line is part of an untouched
+/// equal run -- synthesizes a `Changed` line for it.
+fn ensure_trailing_newline_markers(
+    source: &[String],
+    modified: &[String],
+    ops: &[EditOp],
+    lines: &mut Vec<DiffLine>,
+    anchors: &mut Vec<ContextAnchor>,
+    mod_indices: &mut Vec<Option<usize>>,
+    missing_nl: MissingNewlines,
+) {
+    if missing_nl.source {
+        if let Some(si) = source.len().checked_sub(1) {
+            match anchors.iter().position(|a| matches!(a, ContextAnchor::At(x) if *x == si)) {
+                Some(i) => lines[i].source_missing_nl = true,
+                None => {
+                    if let Some(mi) = equal_pair_for_source(ops, si) {
+                        let mut line = DiffLine::changed(modified[mi].clone(), source[si].clone());
+                        line.source_missing_nl = true;
+                        insert_by_anchor(lines, anchors, mod_indices, line, ContextAnchor::AtNoAfter(si), Some(mi));
                     }
-                } else {
-                    if mod_buf.len() > src_buf.len() {
-						j += mod_buf.len();
-                        for str in mod_buf {
-                            lines.push(DiffLine::added(str));
-                        }
-                    } else {
-						i += src_buf.len();
-                        for str in src_buf {
-                            lines.push(DiffLine::removed(str));
-                        }
+                }
+            }
+        }
+    }
+
+    if missing_nl.modified {
+        if let Some(mi) = modified.len().checked_sub(1) {
+            match mod_indices.iter().position(|x| *x == Some(mi)) {
+                Some(i) => lines[i].modified_missing_nl = true,
+                None => {
+                    if let Some(si) = equal_pair_for_modified(ops, mi) {
+                        let mut line = DiffLine::changed(modified[mi].clone(), source[si].clone());
+                        line.modified_missing_nl = true;
+                        insert_by_anchor(lines, anchors, mod_indices, line, ContextAnchor::AtNoAfter(si), Some(mi));
                     }
                 }
             }
-            (None, Some(m)) => {
-                let line = DiffLine::added(m.to_string());
-                lines.push(line);
+        }
+    }
+}
+
+fn compute_diff(
+    source: Vec<String>,
+    modified: Vec<String>,
+    algorithm: DiffAlgorithm,
+    source_missing_nl: bool,
+    modified_missing_nl: bool,
+) -> Vec<DiffLine> {
+    let ops = run_algorithm(&source, &modified, algorithm);
+    let (mut lines, mut anchors, mut mod_indices) = ops_to_diff_lines(&source, &modified, &ops);
+    ensure_trailing_newline_markers(
+        &source,
+        &modified,
+        &ops,
+        &mut lines,
+        &mut anchors,
+        &mut mod_indices,
+        MissingNewlines {
+            source: source_missing_nl,
+            modified: modified_missing_nl,
+        },
+    );
+    populate_context(&source, &mut lines, &anchors);
+    lines
+}
+
+/// Counts occurrences of each line so `unique_common_pairs` can tell which
+/// ones are safe to use as patience-diff anchors.
+fn count_lines(lines: &[String]) -> HashMap<&str, usize> {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for l in lines {
+        *counts.entry(l.as_str()).or_insert(0) += 1;
+    }
+    counts
+}
+
+/// Pairs up lines that occur exactly once in both `source[s_range]` and
+/// `modified[m_range]`, ordered by their `source` position. These are the
+/// candidate anchors for patience diff.
+fn unique_common_pairs(
+    source: &[String],
+    modified: &[String],
+    s_range: Range<usize>,
+    m_range: Range<usize>,
+) -> Vec<(usize, usize)> {
+    let s_counts = count_lines(&source[s_range.clone()]);
+    let m_counts = count_lines(&modified[m_range.clone()]);
+
+    let mut m_pos: HashMap<&str, usize> = HashMap::new();
+    for mi in m_range.clone() {
+        let line = modified[mi].as_str();
+        if m_counts.get(line) == Some(&1) {
+            m_pos.insert(line, mi);
+        }
+    }
+
+    let mut pairs = Vec::new();
+    for si in s_range {
+        let line = source[si].as_str();
+        if s_counts.get(line) == Some(&1) {
+            if let Some(&mi) = m_pos.get(line) {
+                pairs.push((si, mi));
             }
-            (Some(s), None) => {
-                let line = DiffLine::removed(s.to_string());
-                lines.push(line);
+        }
+    }
+    pairs
+}
+
+/// Picks the longest order-preserving subsequence of anchor pairs: since
+/// `pairs` is already sorted by `source` index, this is the longest strictly
+/// increasing subsequence of their `modified` indices.
+fn longest_increasing_subsequence(pairs: &[(usize, usize)]) -> Vec<(usize, usize)> {
+    if pairs.is_empty() {
+        return Vec::new();
+    }
+
+    let mut tails: Vec<usize> = Vec::new();
+    let mut prev: Vec<Option<usize>> = vec![None; pairs.len()];
+
+    for i in 0..pairs.len() {
+        let key = pairs[i].1;
+        let mut lo = 0;
+        let mut hi = tails.len();
+        while lo < hi {
+            let mid = (lo + hi) / 2;
+            if pairs[tails[mid]].1 < key {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+        if lo > 0 {
+            prev[i] = Some(tails[lo - 1]);
+        }
+        if lo == tails.len() {
+            tails.push(i);
+        } else {
+            tails[lo] = i;
+        }
+    }
+
+    let mut result = Vec::with_capacity(tails.len());
+    let mut cursor = tails.last().copied();
+    while let Some(i) = cursor {
+        result.push(pairs[i]);
+        cursor = prev[i];
+    }
+    result.reverse();
+    result
+}
+
+/// Runs Myers over a sub-range of `source`/`modified`, remapping the
+/// resulting ops back to absolute indices.
+fn myers_ops_range(
+    source: &[String],
+    modified: &[String],
+    s_range: Range<usize>,
+    m_range: Range<usize>,
+) -> Vec<EditOp> {
+    let ops = myers_ops(&source[s_range.clone()], &modified[m_range.clone()]);
+    ops.into_iter()
+        .map(|op| match op {
+            EditOp::Equal(si, mi) => EditOp::Equal(si + s_range.start, mi + m_range.start),
+            EditOp::Delete(si) => EditOp::Delete(si + s_range.start),
+            EditOp::Insert(mi) => EditOp::Insert(mi + m_range.start),
+        })
+        .collect()
+}
+
+/// The patience diff technique: anchor on lines that are unique on both
+/// sides, keep the longest order-preserving set of those anchors, then
+/// recurse into the gaps between them (falling back to Myers wherever a gap
+/// has no unique common lines of its own).
+fn patience_ops_range(
+    source: &[String],
+    modified: &[String],
+    s_range: Range<usize>,
+    m_range: Range<usize>,
+) -> Vec<EditOp> {
+    if s_range.is_empty() && m_range.is_empty() {
+        return Vec::new();
+    }
+
+    let pairs = unique_common_pairs(source, modified, s_range.clone(), m_range.clone());
+    let anchors = longest_increasing_subsequence(&pairs);
+
+    if anchors.is_empty() {
+        return myers_ops_range(source, modified, s_range, m_range);
+    }
+
+    let mut ops: Vec<EditOp> = Vec::new();
+    let mut s_cursor = s_range.start;
+    let mut m_cursor = m_range.start;
+
+    for (si, mi) in anchors {
+        ops.extend(patience_ops_range(source, modified, s_cursor..si, m_cursor..mi));
+        ops.push(EditOp::Equal(si, mi));
+        s_cursor = si + 1;
+        m_cursor = mi + 1;
+    }
+    ops.extend(patience_ops_range(
+        source,
+        modified,
+        s_cursor..s_range.end,
+        m_cursor..m_range.end,
+    ));
+
+    ops
+}
+
+fn patience_ops(source: &[String], modified: &[String]) -> Vec<EditOp> {
+    patience_ops_range(source, modified, 0..source.len(), 0..modified.len())
+}
+
+/// A single GNU-style unified diff hunk, already rendered to `-`/`+`/` `
+/// prefixed body lines.
+struct UnifiedHunk {
+    orig_start: usize,
+    orig_len: usize,
+    new_start: usize,
+    new_len: usize,
+    body: Vec<String>,
+}
+
+/// Splits an edit script into ranges (as `[start, end)` indices into `ops`)
+/// that should become unified-diff hunks: each change is padded with up to
+/// `context` lines of surrounding equal ops, and two changes are coalesced
+/// into one hunk whenever at most `2 * context` equal ops separate them.
+fn unified_hunk_ranges(ops: &[EditOp], context: usize) -> Vec<(usize, usize)> {
+    let n = ops.len();
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    let mut i = 0;
+
+    while i < n {
+        if matches!(ops[i], EditOp::Equal(_, _)) {
+            i += 1;
+            continue;
+        }
+
+        let start = i.saturating_sub(context);
+        let mut end = i;
+        loop {
+            while end < n && !matches!(ops[end], EditOp::Equal(_, _)) {
+                end += 1;
             }
-            (None, None) => {}
+            let mut gap = 0;
+            let mut p = end;
+            while p < n && matches!(ops[p], EditOp::Equal(_, _)) && gap <= 2 * context {
+                gap += 1;
+                p += 1;
+            }
+            if p >= n || gap > 2 * context {
+                end = n.min(end + context);
+                break;
+            }
+            end = p;
         }
-        i += 1;
-        j += 1;
+
+        ranges.push((start, end));
+        i = end;
+    }
+
+    ranges
+}
+
+/// Builds unified-diff hunks from an edit script, tracking real source and
+/// modified line numbers through the op stream (something the ffwx format
+/// discards) so each hunk header is accurate even after earlier hunks.
+fn build_unified_hunks(
+    source: &[String],
+    modified: &[String],
+    ops: &[EditOp],
+    context: usize,
+) -> Vec<UnifiedHunk> {
+    let ranges = unified_hunk_ranges(ops, context);
+    let mut hunks = Vec::with_capacity(ranges.len());
+
+    let mut idx = 0usize;
+    let mut s_cursor = 0usize;
+    let mut m_cursor = 0usize;
+
+    for (start, end) in ranges {
+        while idx < start {
+            match ops[idx] {
+                EditOp::Equal(_, _) => {
+                    s_cursor += 1;
+                    m_cursor += 1;
+                }
+                EditOp::Delete(_) => s_cursor += 1,
+                EditOp::Insert(_) => m_cursor += 1,
+            }
+            idx += 1;
+        }
+
+        let orig_start0 = s_cursor;
+        let new_start0 = m_cursor;
+        let mut orig_len = 0;
+        let mut new_len = 0;
+        let mut body: Vec<String> = Vec::new();
+
+        while idx < end {
+            match ops[idx] {
+                EditOp::Equal(si, _) => {
+                    body.push(format!(" {}", source[si]));
+                    orig_len += 1;
+                    new_len += 1;
+                    s_cursor += 1;
+                    m_cursor += 1;
+                }
+                EditOp::Delete(si) => {
+                    body.push(format!("-{}", source[si]));
+                    orig_len += 1;
+                    s_cursor += 1;
+                }
+                EditOp::Insert(mi) => {
+                    body.push(format!("+{}", modified[mi]));
+                    new_len += 1;
+                    m_cursor += 1;
+                }
+            }
+            idx += 1;
+        }
+
+        hunks.push(UnifiedHunk {
+            orig_start: if orig_len == 0 { orig_start0 } else { orig_start0 + 1 },
+            orig_len,
+            new_start: if new_len == 0 { new_start0 } else { new_start0 + 1 },
+            new_len,
+            body,
+        });
     }
 
-    return lines;
+    hunks
+}
+
+fn render_unified_hunks(hunks: &[UnifiedHunk]) -> String {
+    let mut out = String::new();
+    for h in hunks {
+        out.push_str(&format!(
+            "@@ -{},{} +{},{} @@\n",
+            h.orig_start, h.orig_len, h.new_start, h.new_len
+        ));
+        for line in &h.body {
+            out.push_str(line);
+            out.push('\n');
+        }
+    }
+    out
 }
 
 fn write_output<'a, W>(mut w: W, lines: Vec<DiffLine>) -> Result<usize, io::Error>
@@ -309,6 +890,19 @@ where
         // buffer.push_str(CTX_NL);
         buffer.push_str(&h);
         buffer.push_str(&line.value);
+        if let Some(sv) = &line.source_value {
+            buffer.push('\n');
+            buffer.push_str(CHANGED_SOURCE_PREFIX);
+            buffer.push_str(sv);
+        }
+        if line.source_missing_nl {
+            buffer.push('\n');
+            buffer.push_str(NO_NEWLINE_SOURCE);
+        }
+        if line.modified_missing_nl {
+            buffer.push('\n');
+            buffer.push_str(NO_NEWLINE_MODIFIED);
+        }
         // buffer.push_str(CTX_NL);
         buffer.push_str(&line.ctx.after_str());
         buffer.push('\n');
@@ -316,6 +910,517 @@ where
     w.write(buffer.as_bytes())
 }
 
+/// Writes the same diff as `write_output`, but with context lines set off on
+/// their own visually marked rows. The leading `FFWX_HUMAN_MARKER` line lets
+/// `apply` refuse this format outright instead of misreading it.
+fn write_output_human<W>(mut w: W, lines: Vec<DiffLine>) -> Result<usize, io::Error>
+where
+    W: io::Write,
+{
+    let mut buffer = String::new();
+    buffer.push_str(FFWX_HUMAN_MARKER);
+    buffer.push('\n');
+    for line in lines {
+        for b in &line.ctx.before {
+            buffer.push_str("  ");
+            buffer.push_str(b);
+            buffer.push('\n');
+        }
+        buffer.push_str(&line.kind.to_header());
+        buffer.push_str(&line.value);
+        buffer.push('\n');
+        if let Some(sv) = &line.source_value {
+            buffer.push_str(CHANGED_SOURCE_PREFIX);
+            buffer.push_str(sv);
+            buffer.push('\n');
+        }
+        if line.source_missing_nl {
+            buffer.push_str(NO_NEWLINE_SOURCE);
+            buffer.push('\n');
+        }
+        if line.modified_missing_nl {
+            buffer.push_str(NO_NEWLINE_MODIFIED);
+            buffer.push('\n');
+        }
+        for a in &line.ctx.after {
+            buffer.push_str("  ");
+            buffer.push_str(a);
+            buffer.push('\n');
+        }
+        buffer.push('\n');
+    }
+    w.write(buffer.as_bytes())
+}
+
+/// Returns true if `raw` is a diff-line header (`+ `, `- ` or `~ `) rather
+/// than a bare context line.
+fn is_header_line(raw: &str) -> bool {
+    raw.starts_with("+ ") || raw.starts_with("- ") || raw.starts_with("~ ")
+}
+
+fn header_kind(prefix: &str) -> DiffKind {
+    match prefix {
+        "+ " => DiffKind::Added,
+        "- " => DiffKind::Removed,
+        "~ " => DiffKind::Changed,
+        _ => unreachable!("header_kind called with non-header prefix {:?}", prefix),
+    }
+}
+
+/// Splits a run of context lines shared between two adjacent diff lines into
+/// the trailing `after` of the previous one and the leading `before` of the
+/// next one. When the run can't be split evenly the extra line is kept with
+/// `before`, since that's the side that still has a diff line ahead of it to
+/// anchor against.
+fn split_shared_run(run: &[String]) -> (Vec<String>, Vec<String>) {
+    let mid = run.len() / 2;
+    (run[..mid].to_vec(), run[mid..].to_vec())
+}
+
+/// Parses ffwx diff output (the default, non-`-H` format) back into
+/// `DiffLine`s, recovering each line's `LineCtx` from the runs of plain
+/// context lines surrounding it.
+fn parse_ffwx(contents: &str) -> Result<Vec<DiffLine>, io::Error> {
+    if contents.trim_start().starts_with(FFWX_HUMAN_MARKER) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "ffwx file is in human readable (-H) format and cannot be applied",
+        ));
+    }
+
+    let mut raw_lines: Vec<&str> = contents.split('\n').collect();
+    if raw_lines.last() == Some(&"") {
+        raw_lines.pop();
+    }
+
+    let mut entries: Vec<(DiffKind, String, bool, bool, Option<String>)> = Vec::new();
+    let mut runs: Vec<Vec<String>> = Vec::new();
+    let mut current_run: Vec<String> = Vec::new();
+
+    for raw in raw_lines {
+        if is_header_line(raw) {
+            runs.push(std::mem::take(&mut current_run));
+            let (prefix, value) = raw.split_at(2);
+            entries.push((header_kind(prefix), value.to_string(), false, false, None));
+        } else if raw == NO_NEWLINE_SOURCE {
+            if let Some(last) = entries.last_mut() {
+                last.2 = true;
+            }
+        } else if raw == NO_NEWLINE_MODIFIED {
+            if let Some(last) = entries.last_mut() {
+                last.3 = true;
+            }
+        } else if let Some(source_value) = raw.strip_prefix(CHANGED_SOURCE_PREFIX) {
+            if let Some(last) = entries.last_mut() {
+                last.4 = Some(source_value.to_string());
+            }
+        } else {
+            current_run.push(raw.to_string());
+        }
+    }
+    runs.push(current_run);
+
+    let mut befores: Vec<Vec<String>> = vec![Vec::new(); entries.len()];
+    let mut afters: Vec<Vec<String>> = vec![Vec::new(); entries.len()];
+    if let Some(first) = befores.first_mut() {
+        *first = runs[0].clone();
+    }
+    for i in 1..entries.len() {
+        let (after_prev, before_cur) = split_shared_run(&runs[i]);
+        afters[i - 1] = after_prev;
+        befores[i] = before_cur;
+    }
+    if let Some(last) = entries.len().checked_sub(1) {
+        afters[last] = runs[entries.len()].clone();
+    }
+
+    Ok(entries
+        .into_iter()
+        .enumerate()
+        .map(|(i, (kind, value, source_missing_nl, modified_missing_nl, source_value))| {
+            let mut ctx = LineCtx::new();
+            ctx.before = befores[i].clone();
+            ctx.after = afters[i].clone();
+            DiffLine {
+                kind,
+                value,
+                ctx,
+                source_missing_nl,
+                modified_missing_nl,
+                source_value,
+            }
+        })
+        .collect())
+}
+
+/// Returns true if `source[start..start + want.len()]` equals `want`; an
+/// empty `want` always matches, since the absence of context (file edges, or
+/// context simply not captured) must not block anchoring.
+fn slice_matches(source: &[String], start: Option<usize>, want: &[String]) -> bool {
+    if want.is_empty() {
+        return true;
+    }
+    match start {
+        Some(s) => source.get(s..s + want.len()) == Some(want),
+        None => false,
+    }
+}
+
+/// Why `find_anchor` couldn't return a single definite position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AnchorFailure {
+    /// No candidate position matched the context (and value, when checked).
+    NotFound,
+    /// More than one position matched; splicing in either would be a guess.
+    Ambiguous,
+}
+
+/// Finds the position in `source` (at or after `cursor`) anchored by `before`
+/// and `after` context. When `consumes` is true the position names the
+/// source line being removed/changed, so `after` is matched starting one
+/// line past it; otherwise the position is a pure insertion point and `after`
+/// is matched starting right there. When `expected` is set, a candidate
+/// position must also hold that exact line, so two positions that merely
+/// share the same context aren't confused for one another; if more than one
+/// candidate still matches, the anchor is ambiguous rather than guessed at.
+fn find_anchor(
+    source: &[String],
+    cursor: usize,
+    before: &[String],
+    after: &[String],
+    consumes: bool,
+    expected: Option<&str>,
+) -> Result<usize, AnchorFailure> {
+    let upper = if consumes { source.len() } else { source.len() + 1 };
+    let mut found: Option<usize> = None;
+    for p in cursor..upper {
+        let before_start = p.checked_sub(before.len());
+        if !slice_matches(source, before_start, before) {
+            continue;
+        }
+        let after_start = if consumes { p + 1 } else { p };
+        if !slice_matches(source, Some(after_start), after) {
+            continue;
+        }
+        if let Some(want) = expected {
+            if source.get(p).map(String::as_str) != Some(want) {
+                continue;
+            }
+        }
+        match found {
+            None => found = Some(p),
+            Some(_) => return Err(AnchorFailure::Ambiguous),
+        }
+    }
+    found.ok_or(AnchorFailure::NotFound)
+}
+
+fn anchor_error(kind: &DiffKind, value: &str, failure: AnchorFailure) -> io::Error {
+    let reason = match failure {
+        AnchorFailure::NotFound => "surrounding context not found in source",
+        AnchorFailure::Ambiguous => "surrounding context matches more than one place in source",
+    };
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("could not anchor {:?} line {:?}: {}", kind, value, reason),
+    )
+}
+
+/// Reconstructs the modified file by walking `source` and splicing in each
+/// `DiffLine`, using its context to anchor the edit even if line numbers in
+/// `source` have shifted since the diff was produced.
+/// Reconstructs the modified file's lines, plus whether it should end
+/// without a trailing newline.
+fn apply_diff(source: &[String], diff: &[DiffLine]) -> Result<(Vec<String>, bool), io::Error> {
+    let mut output: Vec<String> = Vec::new();
+    let mut cursor = 0usize;
+
+    for line in diff {
+        match line.kind {
+            DiffKind::Added => {
+                let anchor = find_anchor(source, cursor, &line.ctx.before, &line.ctx.after, false, None)
+                    .map_err(|f| anchor_error(&line.kind, &line.value, f))?;
+                output.extend_from_slice(&source[cursor..anchor]);
+                output.push(line.value.clone());
+                cursor = anchor;
+            }
+            DiffKind::Removed => {
+                let anchor = find_anchor(source, cursor, &line.ctx.before, &line.ctx.after, true, Some(&line.value))
+                    .map_err(|f| anchor_error(&line.kind, &line.value, f))?;
+                output.extend_from_slice(&source[cursor..anchor]);
+                cursor = anchor + 1;
+            }
+            DiffKind::Changed => {
+                let expected = line.source_value.as_deref();
+                let anchor = find_anchor(source, cursor, &line.ctx.before, &line.ctx.after, true, expected)
+                    .map_err(|f| anchor_error(&line.kind, &line.value, f))?;
+                output.extend_from_slice(&source[cursor..anchor]);
+                output.push(line.value.clone());
+                cursor = anchor + 1;
+            }
+        }
+    }
+    output.extend_from_slice(&source[cursor..]);
+
+    let missing_trailing_newline = diff.iter().any(|l| l.modified_missing_nl);
+    Ok((output, missing_trailing_newline))
+}
+
+/// Renders `apply_diff`'s output as final file bytes, honoring a missing
+/// trailing newline.
+fn render_applied(lines: &[String], missing_trailing_newline: bool) -> String {
+    let mut out = lines.join("\n");
+    if !missing_trailing_newline {
+        out.push('\n');
+    }
+    out
+}
+
+/// Recursively lists every non-directory entry under `root` (symlinks are
+/// listed as entries, not followed), as paths relative to it, sorted for a deterministic walk order shared by `diff_tree` and
+/// `apply_tree`.
+fn walk_files(root: &Path) -> Result<Vec<PathBuf>, io::Error> {
+    let mut out = Vec::new();
+    walk_files_into(root, Path::new(""), &mut out)?;
+    out.sort();
+    Ok(out)
+}
+
+fn walk_files_into(root: &Path, rel: &Path, out: &mut Vec<PathBuf>) -> Result<(), io::Error> {
+    for entry in fs::read_dir(root.join(rel))? {
+        let entry = entry?;
+        let rel_path = rel.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            walk_files_into(root, &rel_path, out)?;
+        } else {
+            out.push(rel_path);
+        }
+    }
+    Ok(())
+}
+
+/// Merges two sorted relative-path lists (as returned by `walk_files`) into a
+/// single ordered walk, tagging each path with which side(s) it was found on.
+fn merge_paths(source_files: &[PathBuf], modified_files: &[PathBuf]) -> Vec<(PathBuf, bool, bool)> {
+    let mut merged = Vec::with_capacity(source_files.len().max(modified_files.len()));
+    let (mut si, mut mi) = (0usize, 0usize);
+    while si < source_files.len() || mi < modified_files.len() {
+        match (source_files.get(si), modified_files.get(mi)) {
+            (Some(s), Some(m)) if s == m => {
+                merged.push((s.clone(), true, true));
+                si += 1;
+                mi += 1;
+            }
+            (Some(s), Some(m)) if s < m => {
+                merged.push((s.clone(), true, false));
+                si += 1;
+            }
+            (Some(_), Some(m)) => {
+                merged.push((m.clone(), false, true));
+                mi += 1;
+            }
+            (Some(s), None) => {
+                merged.push((s.clone(), true, false));
+                si += 1;
+            }
+            (None, Some(m)) => {
+                merged.push((m.clone(), false, true));
+                mi += 1;
+            }
+            (None, None) => unreachable!("loop condition guarantees at least one side has an entry"),
+        }
+    }
+    merged
+}
+
+/// Reads and diffs one file pair with the requested algorithm; used by
+/// `diff_tree` for paths present on both sides.
+fn diff_pair(source_path: &Path, modified_path: &Path, algorithm: DiffAlgorithm) -> Result<Vec<DiffLine>, io::Error> {
+    let (slines, source_missing_nl) = get_lines_from_file(&source_path.to_path_buf())?;
+    let (mlines, modified_missing_nl) = get_lines_from_file(&modified_path.to_path_buf())?;
+    Ok(compute_diff(slines, mlines, algorithm, source_missing_nl, modified_missing_nl))
+}
+
+/// Writes one file's section of a directory-mode ffwx stream: `marker`
+/// followed by its relative path, then its diff in the requested format.
+fn write_section<W: io::Write>(
+    w: &mut W,
+    marker: &str,
+    rel: &Path,
+    diff: Vec<DiffLine>,
+    human_readable: bool,
+) -> Result<(), io::Error> {
+    let mut header = String::new();
+    header.push_str(marker);
+    header.push_str(&rel.to_string_lossy());
+    header.push('\n');
+    w.write_all(header.as_bytes())?;
+    if human_readable {
+        write_output_human(&mut *w, diff)?;
+    } else {
+        write_output(&mut *w, diff)?;
+    }
+    Ok(())
+}
+
+/// Diffs every file under `source_root` against its counterpart under
+/// `modified_root`, walking both trees and writing one ffwx stream to
+/// `./out.ffwx`: a `FILE_DIFF_MARKER` section per changed file present on
+/// both sides, and a `FILE_ADDED_MARKER`/`FILE_REMOVED_MARKER` section for
+/// paths present on only one side (diffed against an empty file, so the
+/// whole body comes out as `Added`/`Removed` lines). Files are read one at a
+/// time as their turn comes up, so the whole tree is never held in memory at
+/// once. Unchanged files (same content and trailing-newline state) are
+/// skipped entirely.
+fn diff_tree(source_root: &Path, modified_root: &Path, algorithm: DiffAlgorithm, human_readable: bool) -> Result<(), io::Error> {
+    if !source_root.is_dir() || !modified_root.is_dir() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "diffing a directory requires both the source and modified paths to be directories",
+        ));
+    }
+
+    let paths = merge_paths(&walk_files(source_root)?, &walk_files(modified_root)?);
+
+    let file = fs::File::create("./out.ffwx")?;
+    let mut w = io::BufWriter::new(file);
+
+    for (rel, in_source, in_modified) in paths {
+        match (in_source, in_modified) {
+            (true, true) => {
+                let diff = diff_pair(&source_root.join(&rel), &modified_root.join(&rel), algorithm)?;
+                // A diff made only of synthesized trailing-newline markers that
+                // change neither a value nor the newline state is no change.
+                let unchanged = diff.iter().all(|l| {
+                    l.kind == DiffKind::Changed
+                        && l.source_value.as_deref() == Some(l.value.as_str())
+                        && l.source_missing_nl == l.modified_missing_nl
+                });
+                if unchanged {
+                    continue;
+                }
+                write_section(&mut w, FILE_DIFF_MARKER, &rel, diff, human_readable)?;
+            }
+            (false, true) => {
+                let (mlines, modified_missing_nl) = get_lines_from_file(&modified_root.join(&rel))?;
+                let diff = compute_diff(Vec::new(), mlines, algorithm, false, modified_missing_nl);
+                write_section(&mut w, FILE_ADDED_MARKER, &rel, diff, human_readable)?;
+            }
+            (true, false) => {
+                let (slines, source_missing_nl) = get_lines_from_file(&source_root.join(&rel))?;
+                let diff = compute_diff(slines, Vec::new(), algorithm, source_missing_nl, false);
+                write_section(&mut w, FILE_REMOVED_MARKER, &rel, diff, human_readable)?;
+            }
+            (false, false) => unreachable!("merge_paths only emits paths present on at least one side"),
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SectionMarker {
+    Changed,
+    Added,
+    Removed,
+}
+
+/// One section of a directory-mode ffwx stream: which file it's about, how
+/// (changed/added/removed), and its raw per-file diff body (parseable by
+/// `parse_ffwx`).
+struct FfwxSection {
+    marker: SectionMarker,
+    path: PathBuf,
+    body: String,
+}
+
+fn is_section_marker(line: &str) -> bool {
+    line.starts_with(FILE_DIFF_MARKER) || line.starts_with(FILE_ADDED_MARKER) || line.starts_with(FILE_REMOVED_MARKER)
+}
+
+/// Splits a directory-mode ffwx stream (as written by `diff_tree`) back into
+/// its per-file `FfwxSection`s.
+fn split_ffwx_sections(contents: &str) -> Result<Vec<FfwxSection>, io::Error> {
+    let mut sections = Vec::new();
+    let mut lines = contents.lines().peekable();
+
+    while let Some(line) = lines.next() {
+        let (marker, rest) = if let Some(r) = line.strip_prefix(FILE_DIFF_MARKER) {
+            (SectionMarker::Changed, r)
+        } else if let Some(r) = line.strip_prefix(FILE_ADDED_MARKER) {
+            (SectionMarker::Added, r)
+        } else if let Some(r) = line.strip_prefix(FILE_REMOVED_MARKER) {
+            (SectionMarker::Removed, r)
+        } else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("expected a file section marker, found {:?}", line),
+            ));
+        };
+
+        let mut body = String::new();
+        while let Some(&next) = lines.peek() {
+            if is_section_marker(next) {
+                break;
+            }
+            body.push_str(lines.next().unwrap());
+            body.push('\n');
+        }
+
+        sections.push(FfwxSection {
+            marker,
+            path: PathBuf::from(rest),
+            body,
+        });
+    }
+
+    Ok(sections)
+}
+
+/// Applies a directory-mode ffwx stream (as produced by `diff_tree`) against
+/// `source_root`, writing the reconstructed tree under `output_dir`: changed
+/// files are rebuilt from their source counterpart, added files are built
+/// from scratch, removed files are simply never written, and every file not
+/// mentioned in the stream is copied across unchanged.
+fn apply_tree(ffwx_contents: &str, source_root: &Path, output_dir: &Path) -> Result<(), io::Error> {
+    let sections = split_ffwx_sections(ffwx_contents)?;
+    let mut touched: Vec<PathBuf> = Vec::new();
+
+    for section in &sections {
+        touched.push(section.path.clone());
+        if section.marker == SectionMarker::Removed {
+            continue;
+        }
+
+        let target = output_dir.join(&section.path);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let diff = parse_ffwx(&section.body)?;
+        let source_lines = match section.marker {
+            SectionMarker::Added => Vec::new(),
+            SectionMarker::Changed => get_lines_from_file(&source_root.join(&section.path))?.0,
+            SectionMarker::Removed => unreachable!("handled above"),
+        };
+        let (lines, missing_trailing_newline) = apply_diff(&source_lines, &diff)?;
+        fs::write(&target, render_applied(&lines, missing_trailing_newline))?;
+    }
+
+    for rel in walk_files(source_root)? {
+        if touched.contains(&rel) {
+            continue;
+        }
+        let target = output_dir.join(&rel);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::copy(source_root.join(&rel), &target)?;
+    }
+
+    Ok(())
+}
+
 fn main() {
     let cli = Cli::parse();
 
@@ -324,6 +1429,23 @@ fn main() {
             let source = PathBuf::from(&args.source_file);
             let modified = PathBuf::from(&args.modified_file);
 
+            let algorithm = if args.patience {
+                DiffAlgorithm::Patience
+            } else {
+                DiffAlgorithm::Myers
+            };
+
+            if source.is_dir() || modified.is_dir() {
+                if args.unified.is_some() {
+                    eprintln!("Error: --unified is not supported when diffing directories");
+                    return;
+                }
+                if let Err(e) = diff_tree(&source, &modified, algorithm, args.human_readable) {
+                    eprintln!("Error diffing directories: {}", e);
+                }
+                return;
+            }
+
             let slines = get_lines_from_file(&source);
             let mlines = get_lines_from_file(&modified);
             if slines.is_err() {
@@ -334,19 +1456,76 @@ fn main() {
                 eprintln!("Error reading modified file: {}", mlines.unwrap_err());
                 return;
             }
-            let slines = slines.unwrap();
-            let mlines = mlines.unwrap();
+            let (slines, source_missing_nl) = slines.unwrap();
+            let (mlines, modified_missing_nl) = mlines.unwrap();
+
+            if let Some(context) = args.unified {
+                let ops = run_algorithm(&slines, &mlines, algorithm);
+                let hunks = build_unified_hunks(&slines, &mlines, &ops, context);
+                print!("{}", render_unified_hunks(&hunks));
+                return;
+            }
 
-            let diff = compute_diff(slines, mlines);
+            let diff = compute_diff(slines, mlines, algorithm, source_missing_nl, modified_missing_nl);
 
-            println!("\ndiff: {:#?}", diff);
             let file = fs::File::create("./out.ffwx");
-            match write_output(file.expect("Error creating output file"), diff) {
-                Err(e) => eprintln!("Error writing output: {}", e),
-                _ => (),
+            let file = file.expect("Error creating output file");
+            let result = if args.human_readable {
+                write_output_human(file, diff)
+            } else {
+                write_output(file, diff)
+            };
+            if let Err(e) = result {
+                eprintln!("Error writing output: {}", e);
+            }
+        }
+        Command::Apply(args) => {
+            let ffwx_path = PathBuf::from(&args.ffwx_file);
+            let source_path = PathBuf::from(&args.source_file);
+
+            let ffwx_contents = match fs::read_to_string(&ffwx_path) {
+                Ok(c) => c,
+                Err(e) => {
+                    eprintln!("Error reading ffwx file: {}", e);
+                    return;
+                }
+            };
+
+            if source_path.is_dir() {
+                let output_dir = match &args.output_dir {
+                    Some(d) => PathBuf::from(d),
+                    None => {
+                        eprintln!("Error: -o/--out-dir is required when applying against a directory");
+                        return;
+                    }
+                };
+                if let Err(e) = apply_tree(&ffwx_contents, &source_path, &output_dir) {
+                    eprintln!("Error applying directory diff: {}", e);
+                }
+                return;
+            }
+
+            let diff = match parse_ffwx(&ffwx_contents) {
+                Ok(d) => d,
+                Err(e) => {
+                    eprintln!("Error parsing ffwx file: {}", e);
+                    return;
+                }
+            };
+            let source = match get_lines_from_file(&source_path) {
+                Ok((s, _)) => s,
+                Err(e) => {
+                    eprintln!("Error reading source file: {}", e);
+                    return;
+                }
+            };
+            match apply_diff(&source, &diff) {
+                Ok((result, missing_trailing_newline)) => {
+                    print!("{}", render_applied(&result, missing_trailing_newline));
+                }
+                Err(e) => eprintln!("Error applying diff: {}", e),
             }
         }
-        Command::Apply(args) => todo!("Apply"),
     }
 }
 
@@ -404,4 +1583,88 @@ mod tests {
     fn should_trim_contigous_ctx_lines() {
         assert_eq!(true, true);
     }
+
+    fn round_trip(source: Vec<String>, modified: Vec<String>, source_missing_nl: bool, modified_missing_nl: bool) {
+        let diff = compute_diff(
+            source.clone(),
+            modified.clone(),
+            DiffAlgorithm::Myers,
+            source_missing_nl,
+            modified_missing_nl,
+        );
+        let (result, result_missing_nl) = apply_diff(&source, &diff).expect("apply_diff failed");
+        assert_eq!(result, modified);
+        assert_eq!(result_missing_nl, modified_missing_nl);
+    }
+
+    #[test]
+    fn trailing_newline_present_on_both_sides_round_trips() {
+        round_trip(gen_lines("a,b,c"), gen_lines("a,B,c"), false, false);
+    }
+
+    #[test]
+    fn missing_trailing_newline_on_both_sides_round_trips() {
+        round_trip(gen_lines("a,b,c"), gen_lines("a,b,C"), true, true);
+    }
+
+    #[test]
+    fn transition_from_present_to_missing_marks_unchanged_last_line() {
+        let source = gen_lines("a,b,c");
+        let modified = gen_lines("a,b,c");
+        let diff = compute_diff(source.clone(), modified.clone(), DiffAlgorithm::Myers, false, true);
+        assert!(diff.iter().any(|l| l.modified_missing_nl));
+        assert!(!diff.iter().any(|l| l.source_missing_nl));
+        let (result, result_missing_nl) = apply_diff(&source, &diff).expect("apply_diff failed");
+        assert_eq!(result, modified);
+        assert!(result_missing_nl);
+    }
+
+    #[test]
+    fn transition_from_missing_to_present_marks_unchanged_last_line() {
+        let source = gen_lines("a,b,c");
+        let modified = gen_lines("a,b,c");
+        let diff = compute_diff(source.clone(), modified.clone(), DiffAlgorithm::Myers, true, false);
+        assert!(diff.iter().any(|l| l.source_missing_nl));
+        assert!(!diff.iter().any(|l| l.modified_missing_nl));
+        let (result, result_missing_nl) = apply_diff(&source, &diff).expect("apply_diff failed");
+        assert_eq!(result, modified);
+        assert!(!result_missing_nl);
+    }
+
+    #[test]
+    fn transition_with_removed_trailing_lines_round_trips() {
+        // The modified file's last surviving line ("b") maps to a source
+        // index that precedes lines the diff also removes ("c"); the
+        // synthesized trailing-newline marker must still land in anchor
+        // order for `apply` to succeed.
+        round_trip(gen_lines("a,b,c"), gen_lines("a,b"), false, true);
+    }
+
+    #[test]
+    fn removes_correct_line_among_repeated_context() {
+        // "Q" sits in an "x ? x" gap that recurs elsewhere in the file
+        // ("x,P,x,Q,x,R,x"); anchoring on context alone finds the first
+        // "x?x" gap and deletes "P" instead of "Q", so the removed line's
+        // own value must be checked too.
+        round_trip(gen_lines("x,P,x,Q,x,R,x"), gen_lines("x,P,x,x,R,x"), false, false);
+    }
+
+    #[test]
+    fn changes_correct_line_among_repeated_context() {
+        // Same repeated-context ambiguity as above, but for a `Changed`
+        // line ("Q" -> "Q2").
+        round_trip(gen_lines("x,P,x,Q,x,R,x"), gen_lines("x,P,x,Q2,x,R,x"), false, false);
+    }
+
+    #[test]
+    fn apply_errors_on_ambiguous_context_instead_of_guessing() {
+        // Two "Q" lines share identical single-line context; neither value
+        // nor context can tell them apart, so `apply` must refuse to guess.
+        let source = gen_lines("x,Q,x,Q,x");
+        let mut line = DiffLine::removed("Q".to_string());
+        line.ctx.before = vec!["x".to_string()];
+        line.ctx.after = vec!["x".to_string()];
+        let result = apply_diff(&source, &[line]);
+        assert!(result.is_err());
+    }
 }